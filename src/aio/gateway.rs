@@ -1,12 +1,20 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, SocketAddrV4, SocketAddrV6};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use super::mapping::{GatewayConfig, Mapping, MappingHandle, MappingRegistry};
+use super::port_mapping::PortMappingConfig;
 use super::soap;
-use crate::errors::{AddAnyPortError, AddPortError, GetExternalIpError, RemovePortError, RequestError};
+use crate::errors::{
+    AddAnyPortError, AddPinholeError, AddPortError, DeletePinholeError, GetExternalIpError,
+    GetGenericPortMappingEntryError, GetPinholeTimeoutError, RemovePortError, RequestError,
+};
 
-use crate::common::{self, parsing::RequestReponse, messages, parsing};
-use crate::PortMappingProtocol;
+use crate::common::{self, parsing::{PortMappingEntry, RequestReponse}, messages, parsing};
+use crate::{PortMappingProtocol, UniqueId};
 
 /// This structure represents a gateway found by the search functions.
 #[derive(Clone, Debug)]
@@ -15,6 +23,14 @@ pub struct Gateway {
     addr: SocketAddrV4,
     /// Control url of the device
     control_url: String,
+    /// Control url of the device's `WANIPv6FirewallControl` service, if the
+    /// device description advertised one.
+    ipv6_firewall_control_url: Option<String>,
+    /// Mappings created through this gateway (and its clones), keyed by
+    /// protocol and external port.
+    mappings: MappingRegistry,
+    /// Default description/lease duration used by the `_default` helpers.
+    config: GatewayConfig,
 }
 
 impl Gateway {
@@ -23,9 +39,121 @@ impl Gateway {
         Gateway {
             addr: addr,
             control_url: control_url,
+            ipv6_firewall_control_url: None,
+            mappings: Arc::new(Mutex::new(HashMap::new())),
+            config: GatewayConfig::default(),
         }
     }
 
+    /// Attach the control URL of the device's `WANIPv6FirewallControl`
+    /// service. Required before calling `add_pinhole`,
+    /// `delete_pinhole` or `get_outbound_pinhole_timeout`.
+    ///
+    /// This tree's gateway search doesn't walk the device description itself
+    /// (there's no `<serviceList>` parsing ahead of this), so the URL isn't
+    /// discovered automatically; callers holding the raw device description
+    /// can get it via [`crate::find_ipv6_firewall_control_url`] instead of
+    /// hand-writing it.
+    pub fn with_ipv6_firewall_control_url(mut self, control_url: String) -> Gateway {
+        self.ipv6_firewall_control_url = Some(control_url);
+        self
+    }
+
+    /// Set the [`GatewayConfig`] used as the default description and lease
+    /// duration for `add_port_default`/`add_any_port_default`.
+    pub fn with_config(mut self, config: GatewayConfig) -> Gateway {
+        self.config = config;
+        self
+    }
+
+    /// Spawn a background task that keeps every finite-lease mapping created
+    /// through this gateway (or any of its clones) alive, re-issuing
+    /// `AddPortMapping` for its tracked external port at roughly half the
+    /// lease interval. This holds for mappings first created through
+    /// `AddAnyPortMapping` too.
+    ///
+    /// Dropping the returned [`MappingHandle`] stops the task and removes
+    /// every mapping it was tracking.
+    pub fn spawn_mapping_handle(&self) -> MappingHandle {
+        MappingHandle::new(self.clone(), self.mappings.clone())
+    }
+
+    /// All mappings currently tracked for this gateway (and its clones).
+    pub fn mapped_ports(&self) -> Vec<Mapping> {
+        self.mappings.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Add a port mapping with any external port, using this gateway's
+    /// configured default description and lease duration.
+    pub async fn add_any_port_default(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddrV4,
+    ) -> Result<u16, AddAnyPortError> {
+        let description = self.config.description().to_owned();
+        self.add_any_port(protocol, local_addr, self.config.lease_duration(), &description)
+            .await
+    }
+
+    /// Add a port mapping, using this gateway's configured default
+    /// description and lease duration.
+    pub async fn add_port_default(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddrV4,
+    ) -> Result<(), AddPortError> {
+        let description = self.config.description().to_owned();
+        self.add_port(protocol, external_port, local_addr, self.config.lease_duration(), &description)
+            .await
+    }
+
+    /// Re-issue `AddPortMapping` for an existing mapping and refresh its
+    /// expiry in the registry. Used by the renewal task spawned from
+    /// [`Gateway::spawn_mapping_handle`].
+    pub(crate) async fn add_port_mapping_tracked(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddrV4,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<(), RequestError> {
+        self.add_port_mapping(protocol, external_port, local_addr, lease_duration, description)
+            .await?;
+        self.track_mapping(protocol, external_port, local_addr, lease_duration, description);
+        Ok(())
+    }
+
+    fn track_mapping(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddrV4,
+        lease_duration: u32,
+        description: &str,
+    ) {
+        let expires_at = if lease_duration == 0 {
+            None
+        } else {
+            Some(Instant::now() + Duration::from_secs(u64::from(lease_duration)))
+        };
+        let mapping = Mapping {
+            protocol,
+            external_port,
+            local_addr,
+            description: description.to_owned(),
+            lease_duration,
+            expires_at,
+            retry_at: None,
+            consecutive_failures: 0,
+        };
+        self.mappings
+            .lock()
+            .unwrap()
+            .insert((protocol, external_port), mapping);
+    }
+
     async fn perform_request(
         &self,
         header: &str,
@@ -33,7 +161,17 @@ impl Gateway {
         ok: &str,
     ) -> Result<RequestReponse, RequestError> {
         let url = format!("{}", self);
-        let text = soap::send_async(&url, soap::Action::new(header), body).await?;
+        self.perform_request_at(&url, header, body, ok).await
+    }
+
+    async fn perform_request_at(
+        &self,
+        url: &str,
+        header: &str,
+        body: &str,
+        ok: &str,
+    ) -> Result<RequestReponse, RequestError> {
+        let text = soap::send_async(url, soap::Action::new(header), body).await?;
         parsing::parse_response(text, ok)
     }
 
@@ -113,7 +251,7 @@ impl Gateway {
                 ),
                 "AddAnyPortMappingResponse",
             ).await;
-        match parsing::parse_add_any_port_mapping_response(resp) {
+        let port = match parsing::parse_add_any_port_mapping_response(resp) {
             Ok(port) => Ok(port),
             Err(None) => {
                 // The router does not have the AddAnyPortMapping method.
@@ -121,7 +259,9 @@ impl Gateway {
                 gateway.retry_add_random_port_mapping(protocol, local_addr, lease_duration, &description).await
             }
             Err(Some(err)) => Err(err),
-        }
+        }?;
+        self.track_mapping(protocol, port, local_addr, lease_duration, &description);
+        Ok(port)
     }
 
     async fn retry_add_random_port_mapping(
@@ -131,51 +271,85 @@ impl Gateway {
         lease_duration: u32,
         description: &str,
     ) -> Result<u16, AddAnyPortError> {
-        for _ in 0u8..20u8 {
-            match self.add_random_port_mapping(protocol, local_addr, lease_duration, &description).await {
-                Ok(port) => return Ok(port),
-                Err(AddAnyPortError::NoPortsAvailable) => continue,
-                e => return e,
-            }
-        }
-        Err(AddAnyPortError::NoPortsAvailable)
+        self.retry_add_port_mapping_with_config(
+            protocol,
+            local_addr,
+            lease_duration,
+            description,
+            &PortMappingConfig::default(),
+        ).await
+        .map(|(port, _)| port)
     }
 
-    async fn add_random_port_mapping(
+    /// Retry `AddPortMapping` with candidate external ports chosen according
+    /// to `config`, up to `config.retry_count()` times. Returns the mapped
+    /// external port and the lease duration it was mapped with (0 after the
+    /// permanent-lease fallback).
+    async fn retry_add_port_mapping_with_config(
         &self,
         protocol: PortMappingProtocol,
         local_addr: SocketAddrV4,
-        lease_duration: u32,
+        mut lease_duration: u32,
         description: &str,
-    ) -> Result<u16, AddAnyPortError> {
-        let description = description.to_owned();
-        let gateway = self.clone();
-
-        let external_port = common::random_port();
-        let res = self.add_port_mapping(protocol, external_port, local_addr, lease_duration, &description).await;
-        
-        match res {
-            Ok(_) => Ok(external_port),
-            Err(err) => match parsing::convert_add_random_port_mapping_error(err) {
-                Some(err) => Err(err),
-                None => gateway.add_same_port_mapping(protocol, local_addr, lease_duration, &description).await
+        config: &PortMappingConfig,
+    ) -> Result<(u16, u32), AddAnyPortError> {
+        let mut attempt = 0;
+        let mut external_port = config.port_strategy().candidate(attempt, local_addr.port());
+        while attempt < config.retry_count() {
+            let res = self.add_port_mapping(protocol, external_port, local_addr, lease_duration, description).await;
+            match res {
+                Ok(_) => return Ok((external_port, lease_duration)),
+                Err(err) => match parsing::convert_add_random_port_mapping_error(err) {
+                    Some(AddAnyPortError::NoPortsAvailable) => {
+                        attempt += 1;
+                        external_port = config.port_strategy().candidate(attempt, local_addr.port());
+                    }
+                    Some(AddAnyPortError::OnlyPermanentLeasesSupported)
+                        if config.retry_with_zero_lease_on_permanent_only() && lease_duration != 0 =>
+                    {
+                        // Retry the same candidate port with an infinite
+                        // lease, rather than burning an attempt moving on to
+                        // a fresh port the gateway hasn't even rejected yet.
+                        lease_duration = 0;
+                    }
+                    Some(err) => return Err(err),
+                    None => {
+                        // SamePortValuesRequired: retry once with matching internal/external ports.
+                        let res = self
+                            .add_port_mapping(protocol, local_addr.port(), local_addr, lease_duration, description)
+                            .await;
+                        return match res {
+                            Ok(_) => Ok((local_addr.port(), lease_duration)),
+                            Err(err) => Err(parsing::convert_add_same_port_mapping_error(err)),
+                        };
+                    }
+                },
             }
         }
+        Err(AddAnyPortError::NoPortsAvailable)
     }
 
-    async fn add_same_port_mapping(
+    /// Add a port mapping with any external port, using a caller-supplied
+    /// [`PortMappingConfig`] for the retry count, external-port selection
+    /// strategy, and whether to transparently retry with an infinite lease
+    /// when the gateway only supports permanent leases.
+    pub async fn add_any_port_with_config(
         &self,
         protocol: PortMappingProtocol,
         local_addr: SocketAddrV4,
         lease_duration: u32,
         description: &str,
+        config: PortMappingConfig,
     ) -> Result<u16, AddAnyPortError> {
-        let res = self
-            .add_port_mapping(protocol, local_addr.port(), local_addr, lease_duration, description).await;
-        match res {
-            Ok(_) => Ok(local_addr.port()),
-            Err(err) => Err(parsing::convert_add_same_port_mapping_error(err))
+        if local_addr.port() == 0 {
+            return Err(AddAnyPortError::InternalPortZeroInvalid);
         }
+
+        let (port, lease_duration) = self
+            .retry_add_port_mapping_with_config(protocol, local_addr, lease_duration, description, &config)
+            .await?;
+        self.track_mapping(protocol, port, local_addr, lease_duration, description);
+        Ok(port)
     }
 
     async fn add_port_mapping(
@@ -224,6 +398,7 @@ impl Gateway {
         if let Err(err) = res {
             return Err(parsing::convert_add_port_error(err));
         };
+        self.track_mapping(protocol, external_port, local_addr, lease_duration, description);
         Ok(())
     }
 
@@ -239,7 +414,130 @@ impl Gateway {
                 &messages::format_delete_port_message(protocol, external_port),
                 "DeletePortMappingResponse",
             ).await;
-        parsing::parse_delete_port_mapping_response(res)
+        parsing::parse_delete_port_mapping_response(res)?;
+        self.mappings.lock().unwrap().remove(&(protocol, external_port));
+        Ok(())
+    }
+
+    /// Get the port mapping table entry at `index` via `GetGenericPortMappingEntry`.
+    ///
+    /// Indices are not stable across calls to `add_port`/`remove_port`; use
+    /// [`Gateway::list_all_mappings`] to read back every entry in one go.
+    pub async fn get_generic_port_mapping_entry(
+        &self,
+        index: u32,
+    ) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
+        let res = self
+            .perform_request(
+                messages::GET_GENERIC_PORT_MAPPING_ENTRY_HEADER,
+                &messages::format_get_generic_port_mapping_entry_message(index),
+                "GetGenericPortMappingEntryResponse",
+            ).await;
+        parsing::parse_get_generic_port_mapping_entry_response(res)
+    }
+
+    /// Get the port mapping table entry for a specific protocol/external port
+    /// via `GetSpecificPortMappingEntry`.
+    pub async fn get_specific_port_mapping_entry(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+    ) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
+        let res = self
+            .perform_request(
+                messages::GET_SPECIFIC_PORT_MAPPING_ENTRY_HEADER,
+                &messages::format_get_specific_port_mapping_entry_message(protocol, external_port),
+                "GetSpecificPortMappingEntryResponse",
+            ).await;
+        parsing::parse_get_specific_port_mapping_entry_response(res, protocol, external_port)
+    }
+
+    /// Read back every port mapping currently on the gateway, by walking
+    /// `GetGenericPortMappingEntry` from index 0 until the gateway reports
+    /// that the index is past the end of its table.
+    pub async fn list_all_mappings(&self) -> Result<Vec<PortMappingEntry>, GetGenericPortMappingEntryError> {
+        let mut entries = Vec::new();
+        let mut index = 0u32;
+        loop {
+            match self.get_generic_port_mapping_entry(index).await {
+                Ok(entry) => entries.push(entry),
+                Err(GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid)
+                | Err(GetGenericPortMappingEntryError::NoSuchEntryInArray) => break,
+                Err(err) => return Err(err),
+            }
+            index += 1;
+        }
+        Ok(entries)
+    }
+
+    fn ipv6_firewall_control_url(&self) -> Result<String, RequestError> {
+        self.ipv6_firewall_control_url
+            .clone()
+            .ok_or_else(|| RequestError::InvalidResponse(
+                "gateway has no WANIPv6FirewallControl service".to_owned(),
+            ))
+    }
+
+    /// Open an IPv6 firewall pinhole via `AddPinhole`, letting traffic from
+    /// `remote` reach `internal` for `lease_duration` seconds (0 is
+    /// infinite). Requires a gateway created with
+    /// [`Gateway::with_ipv6_firewall_control_url`].
+    pub async fn add_pinhole(
+        &self,
+        protocol: PortMappingProtocol,
+        remote: SocketAddrV6,
+        internal: SocketAddrV6,
+        lease_duration: u32,
+    ) -> Result<UniqueId, AddPinholeError> {
+        let url = self
+            .ipv6_firewall_control_url()
+            .map_err(|_| AddPinholeError::NoIpv6FirewallControlUrl)?;
+        let res = self
+            .perform_request_at(
+                &url,
+                messages::ADD_PINHOLE_HEADER,
+                &messages::format_add_pinhole_message(protocol, remote, internal, lease_duration),
+                "AddPinholeResponse",
+            ).await;
+        parsing::parse_add_pinhole_response(res)
+    }
+
+    /// Close a previously opened pinhole via `DeletePinhole`. Requires a
+    /// gateway created with [`Gateway::with_ipv6_firewall_control_url`].
+    pub async fn delete_pinhole(&self, unique_id: UniqueId) -> Result<(), DeletePinholeError> {
+        let url = self
+            .ipv6_firewall_control_url()
+            .map_err(|_| DeletePinholeError::NoIpv6FirewallControlUrl)?;
+        let res = self
+            .perform_request_at(
+                &url,
+                messages::DELETE_PINHOLE_HEADER,
+                &messages::format_delete_pinhole_message(unique_id.0),
+                "DeletePinholeResponse",
+            ).await;
+        parsing::parse_delete_pinhole_response(res)
+    }
+
+    /// Query how long, in seconds, the gateway keeps an outbound pinhole
+    /// open without traffic via `GetOutboundPinholeTimeout`. Requires a
+    /// gateway created with [`Gateway::with_ipv6_firewall_control_url`].
+    pub async fn get_outbound_pinhole_timeout(
+        &self,
+        protocol: PortMappingProtocol,
+        remote: SocketAddrV6,
+        internal: SocketAddrV6,
+    ) -> Result<u32, GetPinholeTimeoutError> {
+        let url = self
+            .ipv6_firewall_control_url()
+            .map_err(|_| GetPinholeTimeoutError::NoIpv6FirewallControlUrl)?;
+        let res = self
+            .perform_request_at(
+                &url,
+                messages::GET_OUTBOUND_PINHOLE_TIMEOUT_HEADER,
+                &messages::format_get_outbound_pinhole_timeout_message(protocol, remote, internal),
+                "GetOutboundPinholeTimeoutResponse",
+            ).await;
+        parsing::parse_get_outbound_pinhole_timeout_response(res)
     }
 }
 
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::Gateway;
+use crate::errors::{RemovePortError, RequestError};
+use crate::PortMappingProtocol;
+
+/// Default lease duration, in seconds, used by [`GatewayConfig`] when none is given.
+const DEFAULT_LEASE_DURATION_SECS: u32 = 3600;
+
+/// Default description used by [`GatewayConfig`] when none is given.
+const DEFAULT_DESCRIPTION: &str = "rust-igd";
+
+/// Initial delay before retrying a mapping whose renewal just failed.
+/// Doubles on each consecutive failure, capped at [`MAX_RENEWAL_BACKOFF`].
+const INITIAL_RENEWAL_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Upper bound on the renewal retry backoff.
+const MAX_RENEWAL_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Number of consecutive renewal failures tolerated before a mapping is
+/// dropped from the registry and no longer retried.
+const MAX_RENEWAL_FAILURES: u32 = 5;
+
+/// Configuration applied to mappings created through a [`Gateway`] when no
+/// per-call override is given.
+///
+/// Mirrors the `Config` used by libp2p's UPnP transport: callers set a
+/// default description and lease duration once, instead of passing them to
+/// every `add_port`/`add_any_port` call.
+#[derive(Clone, Debug)]
+pub struct GatewayConfig {
+    description: String,
+    lease_duration: u32,
+}
+
+impl GatewayConfig {
+    /// Set the description attached to mappings created with this config.
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the lease duration, in seconds, used for mappings created with this config.
+    ///
+    /// A value of `0` means an infinite lease, in which case no renewal is scheduled.
+    pub fn with_lease_duration(mut self, lease_duration: u32) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    pub(crate) fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub(crate) fn lease_duration(&self) -> u32 {
+        self.lease_duration
+    }
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        GatewayConfig {
+            description: DEFAULT_DESCRIPTION.to_owned(),
+            lease_duration: DEFAULT_LEASE_DURATION_SECS,
+        }
+    }
+}
+
+/// A single port mapping created through a [`Gateway`], as tracked by the
+/// gateway's mapping registry.
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub protocol: PortMappingProtocol,
+    pub external_port: u16,
+    pub local_addr: SocketAddrV4,
+    pub description: String,
+    pub lease_duration: u32,
+    /// The instant at which the router will let this mapping expire, computed
+    /// from the lease duration at the time the mapping was (re-)issued.
+    /// `None` for an infinite (`lease_duration == 0`) mapping.
+    pub expires_at: Option<Instant>,
+    /// Overrides `renew_at`'s normal, expiry-based schedule after a failed
+    /// renewal attempt, so the next attempt is delayed by a backoff instead
+    /// of being retried immediately. Cleared on a successful renewal.
+    retry_at: Option<Instant>,
+    /// Number of renewal attempts that have failed in a row since the last
+    /// successful renewal. Drives the backoff and the drop-after-N-failures
+    /// cutoff.
+    consecutive_failures: u32,
+}
+
+impl Mapping {
+    fn renew_at(&self) -> Option<Instant> {
+        if self.retry_at.is_some() {
+            return self.retry_at;
+        }
+        self.expires_at
+            .map(|expiry| expiry - Duration::from_secs(u64::from(self.lease_duration) / 2))
+    }
+}
+
+/// Shared, cloneable registry of mappings a [`Gateway`] has created.
+///
+/// Every clone of a `Gateway` sees the same registry, so mappings added
+/// through one clone (e.g. on a background task) are visible through
+/// another.
+pub(crate) type MappingRegistry = Arc<Mutex<HashMap<(PortMappingProtocol, u16), Mapping>>>;
+
+/// An error reported by a [`MappingHandle`]'s background renewal task.
+#[derive(Debug)]
+pub struct RenewalError {
+    pub protocol: PortMappingProtocol,
+    pub external_port: u16,
+    pub error: RequestError,
+}
+
+/// A handle to the background task that keeps a `Gateway`'s mappings alive.
+///
+/// Dropping the handle stops the renewal task and removes every mapping the
+/// handle is tracking, on a best-effort basis. This cleanup is silently
+/// skipped if the handle is dropped outside a Tokio runtime context.
+pub struct MappingHandle {
+    gateway: Gateway,
+    mappings: MappingRegistry,
+    renewal_task: Option<JoinHandle<()>>,
+    failures: mpsc::UnboundedReceiver<RenewalError>,
+}
+
+impl MappingHandle {
+    pub(crate) fn new(gateway: Gateway, mappings: MappingRegistry) -> Self {
+        let (failure_tx, failures) = mpsc::unbounded_channel();
+        let renewal_task = tokio::spawn(renewal_loop(gateway.clone(), mappings.clone(), failure_tx));
+        MappingHandle {
+            gateway,
+            mappings,
+            renewal_task: Some(renewal_task),
+            failures,
+        }
+    }
+
+    /// Receive the next renewal failure reported by the background task, if any.
+    pub async fn next_failure(&mut self) -> Option<RenewalError> {
+        self.failures.recv().await
+    }
+
+    /// Remove a single mapping, both from the router and from the registry.
+    pub async fn remove(&self, protocol: PortMappingProtocol, external_port: u16) -> Result<(), RemovePortError> {
+        self.gateway.remove_port(protocol, external_port).await?;
+        self.mappings
+            .lock()
+            .unwrap()
+            .remove(&(protocol, external_port));
+        Ok(())
+    }
+
+    /// Remove every mapping currently tracked by this handle.
+    pub async fn remove_all(&self) -> Result<(), RemovePortError> {
+        let keys: Vec<_> = self.mappings.lock().unwrap().keys().cloned().collect();
+        for (protocol, external_port) in keys {
+            self.remove(protocol, external_port).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MappingHandle {
+    /// Best-effort cleanup: if dropped from within a Tokio runtime, spawns a
+    /// task to remove every tracked mapping from the router. If no runtime
+    /// is available (e.g. a synchronous shutdown path), cleanup is skipped
+    /// rather than panicking — callers that need a guaranteed teardown
+    /// should call `remove_all` directly before dropping the handle.
+    fn drop(&mut self) {
+        if let Some(task) = self.renewal_task.take() {
+            task.abort();
+        }
+
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => return,
+        };
+
+        let gateway = self.gateway.clone();
+        let keys: Vec<_> = self.mappings.lock().unwrap().keys().cloned().collect();
+        let mappings = self.mappings.clone();
+        handle.spawn(async move {
+            for (protocol, external_port) in keys {
+                let _ = gateway.remove_port(protocol, external_port).await;
+                mappings.lock().unwrap().remove(&(protocol, external_port));
+            }
+        });
+    }
+}
+
+async fn renewal_loop(
+    gateway: Gateway,
+    mappings: MappingRegistry,
+    failures: mpsc::UnboundedSender<RenewalError>,
+) {
+    loop {
+        let next_wakeup = {
+            let mappings = mappings.lock().unwrap();
+            mappings
+                .values()
+                .filter_map(Mapping::renew_at)
+                .min()
+        };
+
+        let sleep_for = match next_wakeup {
+            Some(at) => at.saturating_duration_since(Instant::now()),
+            // Nothing to renew yet; poll again in a minute in case a new
+            // finite-lease mapping is added in the meantime.
+            None => Duration::from_secs(60),
+        };
+        tokio::time::sleep(sleep_for).await;
+
+        let due: Vec<Mapping> = {
+            let mappings = mappings.lock().unwrap();
+            mappings
+                .values()
+                .filter(|mapping| matches!(mapping.renew_at(), Some(at) if at <= Instant::now()))
+                .cloned()
+                .collect()
+        };
+
+        for mapping in due {
+            let result = gateway
+                .add_port_mapping_tracked(
+                    mapping.protocol,
+                    mapping.external_port,
+                    mapping.local_addr,
+                    mapping.lease_duration,
+                    &mapping.description,
+                )
+                .await;
+
+            if let Err(error) = result {
+                // `add_port_mapping_tracked` only updates the registry on
+                // success, so on failure we have to apply the backoff (or
+                // drop) ourselves; otherwise `renew_at` stays in the past and
+                // the next iteration retries immediately.
+                let key = (mapping.protocol, mapping.external_port);
+                let dropped = {
+                    let mut mappings = mappings.lock().unwrap();
+                    match mappings.get_mut(&key) {
+                        Some(tracked) => {
+                            tracked.consecutive_failures += 1;
+                            if tracked.consecutive_failures >= MAX_RENEWAL_FAILURES {
+                                mappings.remove(&key);
+                                true
+                            } else {
+                                tracked.retry_at = Some(Instant::now() + renewal_backoff(tracked.consecutive_failures));
+                                false
+                            }
+                        }
+                        // Removed (e.g. via `MappingHandle::remove`) while the
+                        // renewal attempt was in flight; nothing to update.
+                        None => false,
+                    }
+                };
+
+                let _ = failures.send(RenewalError {
+                    protocol: mapping.protocol,
+                    external_port: mapping.external_port,
+                    error: if dropped {
+                        RequestError::InvalidResponse(format!(
+                            "{} consecutive renewal failures, mapping dropped: {}",
+                            MAX_RENEWAL_FAILURES, error
+                        ))
+                    } else {
+                        error
+                    },
+                });
+            }
+        }
+    }
+}
+
+/// Backoff applied before retrying a mapping after `consecutive_failures`
+/// (>= 1) renewal failures in a row: doubles each time, capped at
+/// [`MAX_RENEWAL_BACKOFF`].
+fn renewal_backoff(consecutive_failures: u32) -> Duration {
+    INITIAL_RENEWAL_BACKOFF
+        .saturating_mul(1 << (consecutive_failures - 1))
+        .min(MAX_RENEWAL_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(expires_at: Option<Instant>) -> Mapping {
+        Mapping {
+            protocol: PortMappingProtocol::TCP,
+            external_port: 1234,
+            local_addr: SocketAddrV4::new(std::net::Ipv4Addr::new(192, 168, 1, 42), 1234),
+            description: "rust-igd".to_owned(),
+            lease_duration: 3600,
+            expires_at,
+            retry_at: None,
+            consecutive_failures: 0,
+        }
+    }
+
+    #[test]
+    fn renew_at_is_half_the_lease_before_expiry() {
+        let now = Instant::now();
+        let mut m = mapping(Some(now + Duration::from_secs(3600)));
+        m.lease_duration = 3600;
+        assert_eq!(m.renew_at(), Some(now + Duration::from_secs(1800)));
+    }
+
+    #[test]
+    fn renew_at_is_none_for_infinite_lease() {
+        let m = mapping(None);
+        assert_eq!(m.renew_at(), None);
+    }
+
+    #[test]
+    fn retry_at_overrides_expiry_based_schedule() {
+        let now = Instant::now();
+        let mut m = mapping(Some(now + Duration::from_secs(3600)));
+        m.retry_at = Some(now + Duration::from_secs(30));
+        assert_eq!(m.renew_at(), Some(now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn renewal_backoff_doubles_and_caps() {
+        assert_eq!(renewal_backoff(1), Duration::from_secs(30));
+        assert_eq!(renewal_backoff(2), Duration::from_secs(60));
+        assert_eq!(renewal_backoff(3), Duration::from_secs(120));
+        // Keeps doubling well past the cap without overflowing or panicking.
+        assert_eq!(renewal_backoff(10), MAX_RENEWAL_BACKOFF);
+    }
+}
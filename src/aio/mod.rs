@@ -0,0 +1,8 @@
+mod gateway;
+mod mapping;
+mod port_mapping;
+mod soap;
+
+pub use self::gateway::Gateway;
+pub use self::mapping::{GatewayConfig, Mapping, MappingHandle, RenewalError};
+pub use self::port_mapping::{ExternalPortStrategy, PortMappingConfig};
@@ -0,0 +1,150 @@
+use crate::common;
+
+/// How `Gateway::add_any_port_with_config` should pick candidate external
+/// ports to offer the router.
+#[derive(Clone, Debug)]
+pub enum ExternalPortStrategy {
+    /// Pick a fully random ephemeral port for every attempt (the library's original behaviour).
+    Random,
+    /// Try `base`, `base + 1`, `base + 2`, ... on successive attempts.
+    Sequential { base: u16 },
+    /// Try the internal port first, then fall back to fully random ports.
+    PreferSameAsInternal,
+}
+
+impl Default for ExternalPortStrategy {
+    fn default() -> Self {
+        ExternalPortStrategy::Random
+    }
+}
+
+impl ExternalPortStrategy {
+    /// The external port to offer on the `attempt`th (0-indexed) try, given
+    /// the local port the mapping is for.
+    pub(crate) fn candidate(&self, attempt: u32, internal_port: u16) -> u16 {
+        match *self {
+            ExternalPortStrategy::Random => common::random_port(),
+            ExternalPortStrategy::Sequential { base } => {
+                // Port 0 isn't a valid external port; skip over it rather
+                // than offering it to the router.
+                let port = base.wrapping_add(attempt as u16);
+                if port == 0 {
+                    port.wrapping_add(1)
+                } else {
+                    port
+                }
+            }
+            ExternalPortStrategy::PreferSameAsInternal => {
+                if attempt == 0 {
+                    internal_port
+                } else {
+                    common::random_port()
+                }
+            }
+        }
+    }
+}
+
+/// Configuration controlling how `Gateway::add_any_port_with_config` retries
+/// and picks candidate external ports.
+///
+/// Some routers only accept an external port equal to the internal port, or
+/// restrict mappings to a narrow range; this lets callers adapt instead of
+/// relying on the library's fixed 20-random-tries behaviour.
+#[derive(Clone, Debug)]
+pub struct PortMappingConfig {
+    retry_count: u32,
+    port_strategy: ExternalPortStrategy,
+    retry_with_zero_lease_on_permanent_only: bool,
+}
+
+impl PortMappingConfig {
+    /// Set the number of candidate external ports to try before giving up.
+    pub fn with_retry_count(mut self, retry_count: u32) -> Self {
+        self.retry_count = retry_count;
+        self
+    }
+
+    /// Set the strategy used to pick candidate external ports.
+    pub fn with_port_strategy(mut self, port_strategy: ExternalPortStrategy) -> Self {
+        self.port_strategy = port_strategy;
+        self
+    }
+
+    /// If `true`, transparently retry with `lease_duration = 0` when the
+    /// gateway reports `OnlyPermanentLeasesSupported`, instead of returning
+    /// the error to the caller.
+    pub fn with_retry_zero_lease_on_permanent_only(mut self, retry: bool) -> Self {
+        self.retry_with_zero_lease_on_permanent_only = retry;
+        self
+    }
+
+    pub(crate) fn retry_count(&self) -> u32 {
+        self.retry_count
+    }
+
+    pub(crate) fn port_strategy(&self) -> &ExternalPortStrategy {
+        &self.port_strategy
+    }
+
+    pub(crate) fn retry_with_zero_lease_on_permanent_only(&self) -> bool {
+        self.retry_with_zero_lease_on_permanent_only
+    }
+}
+
+impl Default for PortMappingConfig {
+    fn default() -> Self {
+        // Matches the library's original `retry_add_random_port_mapping` behaviour.
+        PortMappingConfig {
+            retry_count: 20,
+            port_strategy: ExternalPortStrategy::Random,
+            retry_with_zero_lease_on_permanent_only: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequential_strategy_counts_up_from_base() {
+        let strategy = ExternalPortStrategy::Sequential { base: 6000 };
+        assert_eq!(strategy.candidate(0, 1234), 6000);
+        assert_eq!(strategy.candidate(1, 1234), 6001);
+        assert_eq!(strategy.candidate(2, 1234), 6002);
+    }
+
+    #[test]
+    fn sequential_strategy_skips_port_zero_on_wraparound() {
+        let strategy = ExternalPortStrategy::Sequential { base: u16::MAX };
+        assert_eq!(strategy.candidate(0, 1234), u16::MAX);
+        // base.wrapping_add(1) would be 0; the strategy must skip it.
+        assert_eq!(strategy.candidate(1, 1234), 1);
+    }
+
+    #[test]
+    fn prefer_same_as_internal_tries_internal_port_first() {
+        let strategy = ExternalPortStrategy::PreferSameAsInternal;
+        assert_eq!(strategy.candidate(0, 4242), 4242);
+    }
+
+    #[test]
+    fn default_config_matches_original_retry_behaviour() {
+        let config = PortMappingConfig::default();
+        assert_eq!(config.retry_count(), 20);
+        assert!(matches!(config.port_strategy(), ExternalPortStrategy::Random));
+        assert!(!config.retry_with_zero_lease_on_permanent_only());
+    }
+
+    #[test]
+    fn builder_methods_override_defaults() {
+        let config = PortMappingConfig::default()
+            .with_retry_count(3)
+            .with_port_strategy(ExternalPortStrategy::Sequential { base: 7000 })
+            .with_retry_zero_lease_on_permanent_only(true);
+        assert_eq!(config.retry_count(), 3);
+        assert!(matches!(config.port_strategy(), ExternalPortStrategy::Sequential { base: 7000 }));
+        assert!(config.retry_with_zero_lease_on_permanent_only());
+    }
+}
@@ -0,0 +1,29 @@
+use std::io;
+
+use crate::errors::RequestError;
+
+/// The SOAPAction HTTP header sent alongside a request body.
+pub struct Action(String);
+
+impl Action {
+    pub fn new(action: &str) -> Action {
+        Action(action.to_owned())
+    }
+}
+
+/// Send a SOAP request to `url` and return the raw response body.
+pub async fn send_async(url: &str, action: Action, body: &str) -> Result<String, RequestError> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(url)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", action.0)
+        .body(body.to_owned())
+        .send()
+        .await
+        .map_err(|e| RequestError::IoError(io::Error::new(io::ErrorKind::Other, e)))?;
+    response
+        .text()
+        .await
+        .map_err(|e| RequestError::IoError(io::Error::new(io::ErrorKind::Other, e)))
+}
@@ -0,0 +1,204 @@
+use std::net::{SocketAddrV4, SocketAddrV6};
+
+use super::SCHEMA_URL;
+use crate::PortMappingProtocol;
+
+/// Namespace used for all SOAP messages sent to the `WANIPv6FirewallControl` service.
+const FIREWALL_SCHEMA_URL: &str = "urn:schemas-upnp-org:service:WANIPv6FirewallControl:1";
+
+pub const GET_EXTERNAL_IP_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPConnection:1#GetExternalIPAddress\"";
+pub const ADD_ANY_PORT_MAPPING_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPConnection:1#AddAnyPortMapping\"";
+pub const ADD_PORT_MAPPING_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping\"";
+pub const DELETE_PORT_MAPPING_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPConnection:1#DeletePortMapping\"";
+pub const GET_GENERIC_PORT_MAPPING_ENTRY_HEADER: &str =
+    "\"urn:schemas-upnp-org:service:WANIPConnection:1#GetGenericPortMappingEntry\"";
+pub const GET_SPECIFIC_PORT_MAPPING_ENTRY_HEADER: &str =
+    "\"urn:schemas-upnp-org:service:WANIPConnection:1#GetSpecificPortMappingEntry\"";
+pub const ADD_PINHOLE_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPv6FirewallControl:1#AddPinhole\"";
+pub const DELETE_PINHOLE_HEADER: &str = "\"urn:schemas-upnp-org:service:WANIPv6FirewallControl:1#DeletePinhole\"";
+pub const GET_OUTBOUND_PINHOLE_TIMEOUT_HEADER: &str =
+    "\"urn:schemas-upnp-org:service:WANIPv6FirewallControl:1#GetOutboundPinholeTimeout\"";
+
+fn envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body>{}</s:Body></s:Envelope>",
+        body
+    )
+}
+
+pub fn format_get_external_ip_message() -> String {
+    envelope(&format!(
+        "<u:GetExternalIPAddress xmlns:u=\"{}\"></u:GetExternalIPAddress>",
+        SCHEMA_URL
+    ))
+}
+
+pub fn format_add_any_port_mapping_message(
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    local_addr: SocketAddrV4,
+    lease_duration: u32,
+    description: &str,
+) -> String {
+    envelope(&format!(
+        "<u:AddAnyPortMapping xmlns:u=\"{schema}\">\
+         <NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{external_port}</NewExternalPort>\
+         <NewProtocol>{protocol}</NewProtocol>\
+         <NewInternalPort>{internal_port}</NewInternalPort>\
+         <NewInternalClient>{internal_client}</NewInternalClient>\
+         <NewEnabled>1</NewEnabled>\
+         <NewPortMappingDescription>{description}</NewPortMappingDescription>\
+         <NewLeaseDuration>{lease_duration}</NewLeaseDuration>\
+         </u:AddAnyPortMapping>",
+        schema = SCHEMA_URL,
+        external_port = external_port,
+        protocol = protocol,
+        internal_port = local_addr.port(),
+        internal_client = local_addr.ip(),
+        description = description,
+        lease_duration = lease_duration,
+    ))
+}
+
+pub fn format_add_port_mapping_message(
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    local_addr: SocketAddrV4,
+    lease_duration: u32,
+    description: &str,
+) -> String {
+    envelope(&format!(
+        "<u:AddPortMapping xmlns:u=\"{schema}\">\
+         <NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{external_port}</NewExternalPort>\
+         <NewProtocol>{protocol}</NewProtocol>\
+         <NewInternalPort>{internal_port}</NewInternalPort>\
+         <NewInternalClient>{internal_client}</NewInternalClient>\
+         <NewEnabled>1</NewEnabled>\
+         <NewPortMappingDescription>{description}</NewPortMappingDescription>\
+         <NewLeaseDuration>{lease_duration}</NewLeaseDuration>\
+         </u:AddPortMapping>",
+        schema = SCHEMA_URL,
+        external_port = external_port,
+        protocol = protocol,
+        internal_port = local_addr.port(),
+        internal_client = local_addr.ip(),
+        description = description,
+        lease_duration = lease_duration,
+    ))
+}
+
+pub fn format_delete_port_message(protocol: PortMappingProtocol, external_port: u16) -> String {
+    envelope(&format!(
+        "<u:DeletePortMapping xmlns:u=\"{schema}\">\
+         <NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{external_port}</NewExternalPort>\
+         <NewProtocol>{protocol}</NewProtocol>\
+         </u:DeletePortMapping>",
+        schema = SCHEMA_URL,
+        external_port = external_port,
+        protocol = protocol,
+    ))
+}
+
+/// Format a `GetGenericPortMappingEntry` request for the mapping at `index`
+/// in the gateway's port mapping table.
+pub fn format_get_generic_port_mapping_entry_message(index: u32) -> String {
+    envelope(&format!(
+        "<u:GetGenericPortMappingEntry xmlns:u=\"{schema}\">\
+         <NewPortMappingIndex>{index}</NewPortMappingIndex>\
+         </u:GetGenericPortMappingEntry>",
+        schema = SCHEMA_URL,
+        index = index,
+    ))
+}
+
+/// Format a `GetSpecificPortMappingEntry` request for the mapping matching
+/// `protocol`/`external_port`.
+pub fn format_get_specific_port_mapping_entry_message(protocol: PortMappingProtocol, external_port: u16) -> String {
+    envelope(&format!(
+        "<u:GetSpecificPortMappingEntry xmlns:u=\"{schema}\">\
+         <NewRemoteHost></NewRemoteHost>\
+         <NewExternalPort>{external_port}</NewExternalPort>\
+         <NewProtocol>{protocol}</NewProtocol>\
+         </u:GetSpecificPortMappingEntry>",
+        schema = SCHEMA_URL,
+        external_port = external_port,
+        protocol = protocol,
+    ))
+}
+
+/// The `WANIPv6FirewallControl` service identifies protocols by their IANA
+/// IP protocol number rather than by name.
+fn ip_protocol_number(protocol: PortMappingProtocol) -> u8 {
+    match protocol {
+        PortMappingProtocol::TCP => 6,
+        PortMappingProtocol::UDP => 17,
+    }
+}
+
+/// Format an `AddPinhole` request opening a pinhole from `remote` to
+/// `internal` for `lease_duration` seconds (0 is infinite).
+pub fn format_add_pinhole_message(
+    protocol: PortMappingProtocol,
+    remote: SocketAddrV6,
+    internal: SocketAddrV6,
+    lease_duration: u32,
+) -> String {
+    envelope(&format!(
+        "<u:AddPinhole xmlns:u=\"{schema}\">\
+         <RemoteHost>{remote_host}</RemoteHost>\
+         <RemotePort>{remote_port}</RemotePort>\
+         <Protocol>{protocol}</Protocol>\
+         <InternalPort>{internal_port}</InternalPort>\
+         <InternalClient>{internal_client}</InternalClient>\
+         <LeaseTime>{lease_duration}</LeaseTime>\
+         </u:AddPinhole>",
+        schema = FIREWALL_SCHEMA_URL,
+        remote_host = remote.ip(),
+        remote_port = remote.port(),
+        protocol = ip_protocol_number(protocol),
+        internal_port = internal.port(),
+        internal_client = internal.ip(),
+        lease_duration = lease_duration,
+    ))
+}
+
+/// Format a `DeletePinhole` request for the pinhole identified by `unique_id`.
+pub fn format_delete_pinhole_message(unique_id: u16) -> String {
+    envelope(&format!(
+        "<u:DeletePinhole xmlns:u=\"{schema}\">\
+         <UniqueID>{unique_id}</UniqueID>\
+         </u:DeletePinhole>",
+        schema = FIREWALL_SCHEMA_URL,
+        unique_id = unique_id,
+    ))
+}
+
+/// Format a `GetOutboundPinholeTimeout` request, used to probe how long the
+/// gateway keeps an outbound pinhole open without traffic.
+pub fn format_get_outbound_pinhole_timeout_message(
+    protocol: PortMappingProtocol,
+    remote: SocketAddrV6,
+    internal: SocketAddrV6,
+) -> String {
+    envelope(&format!(
+        "<u:GetOutboundPinholeTimeout xmlns:u=\"{schema}\">\
+         <RemoteHost>{remote_host}</RemoteHost>\
+         <RemotePort>{remote_port}</RemotePort>\
+         <Protocol>{protocol}</Protocol>\
+         <InternalPort>{internal_port}</InternalPort>\
+         <InternalClient>{internal_client}</InternalClient>\
+         </u:GetOutboundPinholeTimeout>",
+        schema = FIREWALL_SCHEMA_URL,
+        remote_host = remote.ip(),
+        remote_port = remote.port(),
+        protocol = ip_protocol_number(protocol),
+        internal_port = internal.port(),
+        internal_client = internal.ip(),
+    ))
+}
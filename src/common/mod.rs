@@ -0,0 +1,12 @@
+pub mod messages;
+pub mod parsing;
+
+use rand::Rng;
+
+/// Namespace used for all SOAP messages sent to the gateway's `WANIPConnection` service.
+pub const SCHEMA_URL: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+
+/// Pick a random ephemeral port to try when mapping with a fully random strategy.
+pub fn random_port() -> u16 {
+    rand::thread_rng().gen_range(1025..=65535)
+}
@@ -0,0 +1,377 @@
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::str::FromStr;
+
+use crate::errors::{
+    AddAnyPortError, AddPinholeError, AddPortError, DeletePinholeError, GetExternalIpError,
+    GetGenericPortMappingEntryError, GetPinholeTimeoutError, RemovePortError, RequestError,
+};
+use crate::{PortMappingProtocol, UniqueId};
+
+/// The raw body of a successful SOAP response, envelope included.
+#[derive(Debug)]
+pub struct RequestReponse(String);
+
+/// Extract the text content of the first `<tag>...</tag>` occurrence in `xml`.
+fn extract_tag<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+fn upnp_error(xml: &str) -> Option<(u16, String)> {
+    let code = extract_tag(xml, "errorCode")?.trim().parse().ok()?;
+    let description = extract_tag(xml, "errorDescription").unwrap_or("").to_owned();
+    Some((code, description))
+}
+
+/// Parse a raw SOAP response body, returning the body wrapped in a
+/// `RequestReponse` on success (the response contains the `ok` tag), or the
+/// gateway's SOAP fault as a `RequestError` otherwise.
+pub fn parse_response(text: String, ok: &str) -> Result<RequestReponse, RequestError> {
+    if text.contains(ok) {
+        return Ok(RequestReponse(text));
+    }
+    match upnp_error(&text) {
+        Some((code, description)) => Err(RequestError::ErrorCode(code, description)),
+        None => Err(RequestError::InvalidResponse(text)),
+    }
+}
+
+pub fn parse_get_external_ip_response(
+    response: Result<RequestReponse, RequestError>,
+) -> Result<Ipv4Addr, GetExternalIpError> {
+    let RequestReponse(text) = response?;
+    let ip = extract_tag(&text, "NewExternalIPAddress")
+        .ok_or_else(|| GetExternalIpError::RequestError(RequestError::InvalidResponse(text.clone())))?;
+    Ipv4Addr::from_str(ip.trim())
+        .map_err(|_| GetExternalIpError::RequestError(RequestError::InvalidResponse(text)))
+}
+
+pub fn parse_add_any_port_mapping_response(
+    response: Result<RequestReponse, RequestError>,
+) -> Result<u16, Option<AddAnyPortError>> {
+    let RequestReponse(text) = response.map_err(|err| match err {
+        RequestError::ErrorCode(401, _) => None, // Unknown action - fall back to AddPortMapping.
+        RequestError::ErrorCode(606, _) => Some(AddAnyPortError::ActionNotAuthorized),
+        RequestError::ErrorCode(725, _) => Some(AddAnyPortError::OnlyPermanentLeasesSupported),
+        RequestError::ErrorCode(728, _) => Some(AddAnyPortError::NoPortsAvailable),
+        err => Some(AddAnyPortError::RequestError(err)),
+    })?;
+    let port = extract_tag(&text, "NewReservedPort")
+        .and_then(|p| p.trim().parse().ok())
+        .ok_or_else(|| Some(AddAnyPortError::RequestError(RequestError::InvalidResponse(text))))?;
+    Ok(port)
+}
+
+/// Convert an error from the random-port `AddPortMapping` attempt. Returns
+/// `None` when the caller should retry with the local port instead
+/// (`SamePortValuesRequired`), and `Some` for every other error. The caller
+/// may still retry some of these, such as `NoPortsAvailable`.
+pub fn convert_add_random_port_mapping_error(err: RequestError) -> Option<AddAnyPortError> {
+    match err {
+        RequestError::ErrorCode(606, _) => Some(AddAnyPortError::ActionNotAuthorized),
+        RequestError::ErrorCode(718, _) => Some(AddAnyPortError::NoPortsAvailable), // ConflictInMappingEntry
+        RequestError::ErrorCode(724, _) => None,                                   // SamePortValuesRequired
+        RequestError::ErrorCode(725, _) => Some(AddAnyPortError::OnlyPermanentLeasesSupported),
+        err => Some(AddAnyPortError::RequestError(err)),
+    }
+}
+
+pub fn convert_add_same_port_mapping_error(err: RequestError) -> AddAnyPortError {
+    match err {
+        RequestError::ErrorCode(606, _) => AddAnyPortError::ActionNotAuthorized,
+        RequestError::ErrorCode(718, _) => AddAnyPortError::NoPortsAvailable,
+        RequestError::ErrorCode(725, _) => AddAnyPortError::OnlyPermanentLeasesSupported,
+        err => AddAnyPortError::RequestError(err),
+    }
+}
+
+pub fn convert_add_port_error(err: RequestError) -> AddPortError {
+    match err {
+        RequestError::ErrorCode(606, _) => AddPortError::ActionNotAuthorized,
+        RequestError::ErrorCode(718, _) => AddPortError::PortInUse,
+        RequestError::ErrorCode(724, _) => AddPortError::SamePortValuesRequired,
+        RequestError::ErrorCode(725, _) => AddPortError::OnlyPermanentLeasesSupported,
+        err => AddPortError::RequestError(err),
+    }
+}
+
+pub fn parse_delete_port_mapping_response(
+    response: Result<RequestReponse, RequestError>,
+) -> Result<(), RemovePortError> {
+    match response {
+        Ok(_) => Ok(()),
+        Err(RequestError::ErrorCode(606, _)) => Err(RemovePortError::ActionNotAuthorized),
+        Err(RequestError::ErrorCode(714, _)) => Err(RemovePortError::NoSuchPortMapping),
+        Err(err) => Err(RemovePortError::RequestError(err)),
+    }
+}
+
+/// A single entry read back from the gateway's port mapping table via
+/// `GetGenericPortMappingEntry`/`GetSpecificPortMappingEntry`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortMappingEntry {
+    pub protocol: PortMappingProtocol,
+    pub external_port: u16,
+    pub internal_client: SocketAddrV4,
+    pub enabled: bool,
+    pub description: String,
+    pub lease_duration: u32,
+}
+
+/// Parse the 5 out-args common to both `GetGenericPortMappingEntry` and
+/// `GetSpecificPortMappingEntry` responses (`NewInternalPort`,
+/// `NewInternalClient`, `NewEnabled`, `NewPortMappingDescription`,
+/// `NewLeaseDuration`), given the `protocol`/`external_port` the entry is
+/// for.
+fn parse_port_mapping_entry_fields(
+    text: &str,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
+    let invalid = || GetGenericPortMappingEntryError::RequestError(RequestError::InvalidResponse(text.to_owned()));
+
+    let internal_port: u16 = extract_tag(text, "NewInternalPort")
+        .and_then(|p| p.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let internal_client: Ipv4Addr = extract_tag(text, "NewInternalClient")
+        .and_then(|ip| Ipv4Addr::from_str(ip.trim()).ok())
+        .ok_or_else(invalid)?;
+    let enabled = extract_tag(text, "NewEnabled").map(|e| e.trim() == "1").unwrap_or(false);
+    let description = extract_tag(text, "NewPortMappingDescription").unwrap_or("").to_owned();
+    let lease_duration = extract_tag(text, "NewLeaseDuration")
+        .and_then(|d| d.trim().parse().ok())
+        .ok_or_else(invalid)?;
+
+    Ok(PortMappingEntry {
+        protocol,
+        external_port,
+        internal_client: SocketAddrV4::new(internal_client, internal_port),
+        enabled,
+        description,
+        lease_duration,
+    })
+}
+
+/// Parse a `GetGenericPortMappingEntry` response, which echoes the
+/// protocol/external port of the entry at the requested index as out-args
+/// (`NewProtocol`/`NewExternalPort`), alongside the common 5 fields.
+pub fn parse_get_generic_port_mapping_entry_response(
+    response: Result<RequestReponse, RequestError>,
+) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
+    let RequestReponse(text) = response.map_err(|err| match err {
+        RequestError::ErrorCode(606, _) => GetGenericPortMappingEntryError::ActionNotAuthorized,
+        RequestError::ErrorCode(713, _) => GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid,
+        RequestError::ErrorCode(714, _) => GetGenericPortMappingEntryError::NoSuchEntryInArray,
+        err => GetGenericPortMappingEntryError::RequestError(err),
+    })?;
+
+    let invalid = || GetGenericPortMappingEntryError::RequestError(RequestError::InvalidResponse(text.clone()));
+
+    let protocol = match extract_tag(&text, "NewProtocol").ok_or_else(invalid)?.trim() {
+        "TCP" => PortMappingProtocol::TCP,
+        "UDP" => PortMappingProtocol::UDP,
+        _ => return Err(invalid()),
+    };
+    let external_port = extract_tag(&text, "NewExternalPort")
+        .and_then(|p| p.trim().parse().ok())
+        .ok_or_else(invalid)?;
+
+    parse_port_mapping_entry_fields(&text, protocol, external_port)
+}
+
+/// Parse a `GetSpecificPortMappingEntry` response. Unlike
+/// `GetGenericPortMappingEntry`, the protocol and external port are request
+/// *inputs* here and are not echoed back by the gateway, so they must be
+/// supplied by the caller.
+pub fn parse_get_specific_port_mapping_entry_response(
+    response: Result<RequestReponse, RequestError>,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
+    let RequestReponse(text) = response.map_err(|err| match err {
+        RequestError::ErrorCode(606, _) => GetGenericPortMappingEntryError::ActionNotAuthorized,
+        RequestError::ErrorCode(714, _) => GetGenericPortMappingEntryError::NoSuchEntryInArray,
+        err => GetGenericPortMappingEntryError::RequestError(err),
+    })?;
+
+    parse_port_mapping_entry_fields(&text, protocol, external_port)
+}
+
+pub fn parse_add_pinhole_response(response: Result<RequestReponse, RequestError>) -> Result<UniqueId, AddPinholeError> {
+    let RequestReponse(text) = response.map_err(|err| match err {
+        RequestError::ErrorCode(606, _) => AddPinholeError::ActionNotAuthorized,
+        RequestError::ErrorCode(402, _) => AddPinholeError::InvalidArguments,
+        RequestError::ErrorCode(729, _) => AddPinholeError::NoPinholesAvailable,
+        err => AddPinholeError::RequestError(err),
+    })?;
+    let unique_id = extract_tag(&text, "UniqueID")
+        .and_then(|id| id.trim().parse().ok())
+        .ok_or_else(|| AddPinholeError::RequestError(RequestError::InvalidResponse(text)))?;
+    Ok(UniqueId(unique_id))
+}
+
+pub fn parse_delete_pinhole_response(response: Result<RequestReponse, RequestError>) -> Result<(), DeletePinholeError> {
+    match response {
+        Ok(_) => Ok(()),
+        Err(RequestError::ErrorCode(606, _)) => Err(DeletePinholeError::ActionNotAuthorized),
+        Err(RequestError::ErrorCode(704, _)) => Err(DeletePinholeError::NoSuchEntryInArray),
+        Err(err) => Err(DeletePinholeError::RequestError(err)),
+    }
+}
+
+pub fn parse_get_outbound_pinhole_timeout_response(
+    response: Result<RequestReponse, RequestError>,
+) -> Result<u32, GetPinholeTimeoutError> {
+    let RequestReponse(text) = response.map_err(|err| match err {
+        RequestError::ErrorCode(606, _) => GetPinholeTimeoutError::ActionNotAuthorized,
+        err => GetPinholeTimeoutError::RequestError(err),
+    })?;
+    extract_tag(&text, "NewOutboundPinholeTimeout")
+        .and_then(|t| t.trim().parse().ok())
+        .ok_or_else(|| GetPinholeTimeoutError::RequestError(RequestError::InvalidResponse(text)))
+}
+
+/// Find the `controlURL` of the `WANIPv6FirewallControl` service advertised
+/// in a device description XML document, if any.
+///
+/// This repo's gateway search/description-parsing path (the code that would
+/// normally call this while walking a device's `<serviceList>` to build the
+/// IPv4 `Gateway`) isn't part of this tree, so there's nowhere upstream of
+/// [`crate::aio::Gateway::with_ipv6_firewall_control_url`] to wire this into
+/// automatically. Callers that already have the device description in hand
+/// (e.g. from their own SSDP/description fetch) can use this directly instead
+/// of hand-writing the control URL.
+pub fn find_ipv6_firewall_control_url(device_description: &str) -> Option<String> {
+    let service_type_tag = "<serviceType>";
+    let mut search_from = 0;
+    loop {
+        let rel_start = device_description[search_from..].find(service_type_tag)?;
+        let start = search_from + rel_start + service_type_tag.len();
+        let end = start + device_description[start..].find("</serviceType>")?;
+        let service_type = &device_description[start..end];
+
+        if service_type.contains("WANIPv6FirewallControl") {
+            let control_url = extract_tag(&device_description[end..], "controlURL")?;
+            return Some(control_url.trim().to_owned());
+        }
+
+        search_from = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(body: &str) -> Result<RequestReponse, RequestError> {
+        Ok(RequestReponse(body.to_owned()))
+    }
+
+    #[test]
+    fn parses_generic_port_mapping_entry() {
+        let body = "<NewProtocol>TCP</NewProtocol>\
+                     <NewExternalPort>1234</NewExternalPort>\
+                     <NewInternalPort>1234</NewInternalPort>\
+                     <NewInternalClient>192.168.1.42</NewInternalClient>\
+                     <NewEnabled>1</NewEnabled>\
+                     <NewPortMappingDescription>rust-igd</NewPortMappingDescription>\
+                     <NewLeaseDuration>3600</NewLeaseDuration>";
+        let entry = parse_get_generic_port_mapping_entry_response(ok(body)).unwrap();
+        assert_eq!(entry.protocol, PortMappingProtocol::TCP);
+        assert_eq!(entry.external_port, 1234);
+        assert_eq!(entry.internal_client, SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 42), 1234));
+        assert!(entry.enabled);
+        assert_eq!(entry.description, "rust-igd");
+        assert_eq!(entry.lease_duration, 3600);
+    }
+
+    #[test]
+    fn generic_port_mapping_entry_maps_array_index_errors() {
+        let err = parse_get_generic_port_mapping_entry_response(Err(RequestError::ErrorCode(713, String::new())));
+        assert!(matches!(err, Err(GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid)));
+
+        let err = parse_get_generic_port_mapping_entry_response(Err(RequestError::ErrorCode(714, String::new())));
+        assert!(matches!(err, Err(GetGenericPortMappingEntryError::NoSuchEntryInArray)));
+    }
+
+    #[test]
+    fn parses_specific_port_mapping_entry_without_echoed_protocol_or_port() {
+        // A real GetSpecificPortMappingEntry response has no NewProtocol/NewExternalPort.
+        let body = "<NewInternalPort>1234</NewInternalPort>\
+                     <NewInternalClient>192.168.1.42</NewInternalClient>\
+                     <NewEnabled>1</NewEnabled>\
+                     <NewPortMappingDescription>rust-igd</NewPortMappingDescription>\
+                     <NewLeaseDuration>3600</NewLeaseDuration>";
+        let entry =
+            parse_get_specific_port_mapping_entry_response(ok(body), PortMappingProtocol::UDP, 1234).unwrap();
+        assert_eq!(entry.protocol, PortMappingProtocol::UDP);
+        assert_eq!(entry.external_port, 1234);
+        assert_eq!(entry.internal_client, SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 42), 1234));
+        assert_eq!(entry.lease_duration, 3600);
+    }
+
+    #[test]
+    fn parse_add_any_port_mapping_response_reads_reserved_port() {
+        let body = "<NewReservedPort>5678</NewReservedPort>";
+        assert_eq!(parse_add_any_port_mapping_response(ok(body)).unwrap(), 5678);
+    }
+
+    #[test]
+    fn parse_add_any_port_mapping_response_falls_back_on_unknown_action() {
+        let err = parse_add_any_port_mapping_response(Err(RequestError::ErrorCode(401, String::new())));
+        assert!(matches!(err, Err(None)));
+    }
+
+    #[test]
+    fn parse_delete_port_mapping_response_maps_no_such_entry() {
+        let err = parse_delete_port_mapping_response(Err(RequestError::ErrorCode(714, String::new())));
+        assert!(matches!(err, Err(RemovePortError::NoSuchPortMapping)));
+    }
+
+    #[test]
+    fn parses_add_pinhole_response() {
+        let body = "<UniqueID>7</UniqueID>";
+        assert_eq!(parse_add_pinhole_response(ok(body)).unwrap(), UniqueId(7));
+    }
+
+    #[test]
+    fn add_pinhole_response_maps_no_pinholes_available() {
+        let err = parse_add_pinhole_response(Err(RequestError::ErrorCode(729, String::new())));
+        assert!(matches!(err, Err(AddPinholeError::NoPinholesAvailable)));
+    }
+
+    #[test]
+    fn parses_get_outbound_pinhole_timeout_response() {
+        let body = "<NewOutboundPinholeTimeout>300</NewOutboundPinholeTimeout>";
+        assert_eq!(parse_get_outbound_pinhole_timeout_response(ok(body)).unwrap(), 300);
+    }
+
+    #[test]
+    fn finds_ipv6_firewall_control_url_among_several_services() {
+        let description = "<serviceList>\
+             <service>\
+               <serviceType>urn:schemas-upnp-org:service:WANIPConnection:2</serviceType>\
+               <controlURL>/ipv4/control</controlURL>\
+             </service>\
+             <service>\
+               <serviceType>urn:schemas-upnp-org:service:WANIPv6FirewallControl:1</serviceType>\
+               <controlURL>/ipv6/control</controlURL>\
+             </service>\
+           </serviceList>";
+        assert_eq!(find_ipv6_firewall_control_url(description).as_deref(), Some("/ipv6/control"));
+    }
+
+    #[test]
+    fn find_ipv6_firewall_control_url_returns_none_when_absent() {
+        let description = "<serviceList>\
+             <service>\
+               <serviceType>urn:schemas-upnp-org:service:WANIPConnection:2</serviceType>\
+               <controlURL>/ipv4/control</controlURL>\
+             </service>\
+           </serviceList>";
+        assert_eq!(find_ipv6_firewall_control_url(description), None);
+    }
+}
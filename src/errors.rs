@@ -0,0 +1,315 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Errors that can occur when sending the request to the gateway.
+#[derive(Debug)]
+pub enum RequestError {
+    /// Http/IO error communicating with the gateway.
+    IoError(io::Error),
+    /// The response body could not be parsed.
+    InvalidResponse(String),
+    /// The gateway returned a SOAP fault with the given UPnP error code and description.
+    ErrorCode(u16, String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestError::IoError(ref e) => write!(f, "IO error: {}", e),
+            RequestError::InvalidResponse(ref s) => write!(f, "Invalid response from gateway: {}", s),
+            RequestError::ErrorCode(n, ref s) => write!(f, "Gateway response error {}: {}", n, s),
+        }
+    }
+}
+
+impl error::Error for RequestError {}
+
+impl From<io::Error> for RequestError {
+    fn from(err: io::Error) -> RequestError {
+        RequestError::IoError(err)
+    }
+}
+
+/// Errors returned by `Gateway::get_external_ip`.
+#[derive(Debug)]
+pub enum GetExternalIpError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for GetExternalIpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetExternalIpError::ActionNotAuthorized => {
+                write!(f, "The client is not authorized to get the external IP address")
+            }
+            GetExternalIpError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for GetExternalIpError {}
+
+impl From<RequestError> for GetExternalIpError {
+    fn from(err: RequestError) -> GetExternalIpError {
+        GetExternalIpError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::add_any_port`/`Gateway::get_any_address`.
+#[derive(Debug)]
+pub enum AddAnyPortError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// The internal port supplied was 0, which is invalid.
+    InternalPortZeroInvalid,
+    /// The gateway does not have any ports available.
+    NoPortsAvailable,
+    /// The gateway only supports permanent leases.
+    OnlyPermanentLeasesSupported,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for AddAnyPortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddAnyPortError::ActionNotAuthorized => write!(f, "The client is not authorized to map this port"),
+            AddAnyPortError::InternalPortZeroInvalid => write!(f, "Internal port 0 is invalid"),
+            AddAnyPortError::NoPortsAvailable => write!(f, "The gateway has no ports available"),
+            AddAnyPortError::OnlyPermanentLeasesSupported => write!(f, "The gateway only supports permanent leases"),
+            AddAnyPortError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for AddAnyPortError {}
+
+impl From<RequestError> for AddAnyPortError {
+    fn from(err: RequestError) -> AddAnyPortError {
+        AddAnyPortError::RequestError(err)
+    }
+}
+
+impl From<GetExternalIpError> for AddAnyPortError {
+    fn from(err: GetExternalIpError) -> AddAnyPortError {
+        match err {
+            GetExternalIpError::ActionNotAuthorized => AddAnyPortError::ActionNotAuthorized,
+            GetExternalIpError::RequestError(err) => AddAnyPortError::RequestError(err),
+        }
+    }
+}
+
+/// Errors returned by `Gateway::add_port`.
+#[derive(Debug)]
+pub enum AddPortError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// The external port supplied was 0, which is invalid.
+    ExternalPortZeroInvalid,
+    /// The internal port supplied was 0, which is invalid.
+    InternalPortZeroInvalid,
+    /// The external port is already mapped to another internal client.
+    PortInUse,
+    /// The gateway requires the external and internal ports to match.
+    SamePortValuesRequired,
+    /// The gateway only supports permanent leases.
+    OnlyPermanentLeasesSupported,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for AddPortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddPortError::ActionNotAuthorized => write!(f, "The client is not authorized to map this port"),
+            AddPortError::ExternalPortZeroInvalid => write!(f, "External port 0 is invalid"),
+            AddPortError::InternalPortZeroInvalid => write!(f, "Internal port 0 is invalid"),
+            AddPortError::PortInUse => write!(f, "The external port is already in use"),
+            AddPortError::SamePortValuesRequired => write!(f, "The gateway requires the same external and internal port"),
+            AddPortError::OnlyPermanentLeasesSupported => write!(f, "The gateway only supports permanent leases"),
+            AddPortError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for AddPortError {}
+
+impl From<RequestError> for AddPortError {
+    fn from(err: RequestError) -> AddPortError {
+        AddPortError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::remove_port`.
+#[derive(Debug)]
+pub enum RemovePortError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// There is no such port mapping.
+    NoSuchPortMapping,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for RemovePortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RemovePortError::ActionNotAuthorized => write!(f, "The client is not authorized to remove this port"),
+            RemovePortError::NoSuchPortMapping => write!(f, "There is no such port mapping"),
+            RemovePortError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for RemovePortError {}
+
+impl From<RequestError> for RemovePortError {
+    fn from(err: RequestError) -> RemovePortError {
+        RemovePortError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::get_generic_port_mapping_entry`,
+/// `Gateway::get_specific_port_mapping_entry` and `Gateway::list_all_mappings`.
+#[derive(Debug)]
+pub enum GetGenericPortMappingEntryError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// The supplied index is past the end of the port mapping table.
+    SpecifiedArrayIndexInvalid,
+    /// There is no entry for the requested protocol/external port pair.
+    NoSuchEntryInArray,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for GetGenericPortMappingEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetGenericPortMappingEntryError::ActionNotAuthorized => {
+                write!(f, "The client is not authorized to read this port mapping")
+            }
+            GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid => {
+                write!(f, "The specified array index is past the end of the port mapping table")
+            }
+            GetGenericPortMappingEntryError::NoSuchEntryInArray => write!(f, "There is no such entry in the array"),
+            GetGenericPortMappingEntryError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for GetGenericPortMappingEntryError {}
+
+impl From<RequestError> for GetGenericPortMappingEntryError {
+    fn from(err: RequestError) -> GetGenericPortMappingEntryError {
+        GetGenericPortMappingEntryError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::add_pinhole`.
+#[derive(Debug)]
+pub enum AddPinholeError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// The requested internal or remote address/port is invalid.
+    InvalidArguments,
+    /// The gateway does not have any pinholes available.
+    NoPinholesAvailable,
+    /// The gateway was not discovered with a `WANIPv6FirewallControl` service.
+    NoIpv6FirewallControlUrl,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for AddPinholeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddPinholeError::ActionNotAuthorized => write!(f, "The client is not authorized to add this pinhole"),
+            AddPinholeError::InvalidArguments => write!(f, "The supplied pinhole arguments are invalid"),
+            AddPinholeError::NoPinholesAvailable => write!(f, "The gateway has no pinholes available"),
+            AddPinholeError::NoIpv6FirewallControlUrl => {
+                write!(f, "The gateway does not expose a WANIPv6FirewallControl service")
+            }
+            AddPinholeError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for AddPinholeError {}
+
+impl From<RequestError> for AddPinholeError {
+    fn from(err: RequestError) -> AddPinholeError {
+        AddPinholeError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::delete_pinhole`.
+#[derive(Debug)]
+pub enum DeletePinholeError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// There is no pinhole with the given `UniqueId`.
+    NoSuchEntryInArray,
+    /// The gateway was not discovered with a `WANIPv6FirewallControl` service.
+    NoIpv6FirewallControlUrl,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for DeletePinholeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeletePinholeError::ActionNotAuthorized => write!(f, "The client is not authorized to remove this pinhole"),
+            DeletePinholeError::NoSuchEntryInArray => write!(f, "There is no pinhole with this unique id"),
+            DeletePinholeError::NoIpv6FirewallControlUrl => {
+                write!(f, "The gateway does not expose a WANIPv6FirewallControl service")
+            }
+            DeletePinholeError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for DeletePinholeError {}
+
+impl From<RequestError> for DeletePinholeError {
+    fn from(err: RequestError) -> DeletePinholeError {
+        DeletePinholeError::RequestError(err)
+    }
+}
+
+/// Errors returned by `Gateway::get_outbound_pinhole_timeout`.
+#[derive(Debug)]
+pub enum GetPinholeTimeoutError {
+    /// The client is not authorized to perform this action.
+    ActionNotAuthorized,
+    /// The gateway was not discovered with a `WANIPv6FirewallControl` service.
+    NoIpv6FirewallControlUrl,
+    /// An error occurred while performing the request.
+    RequestError(RequestError),
+}
+
+impl fmt::Display for GetPinholeTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetPinholeTimeoutError::ActionNotAuthorized => {
+                write!(f, "The client is not authorized to read this pinhole's timeout")
+            }
+            GetPinholeTimeoutError::NoIpv6FirewallControlUrl => {
+                write!(f, "The gateway does not expose a WANIPv6FirewallControl service")
+            }
+            GetPinholeTimeoutError::RequestError(ref e) => write!(f, "Request error: {}", e),
+        }
+    }
+}
+
+impl error::Error for GetPinholeTimeoutError {}
+
+impl From<RequestError> for GetPinholeTimeoutError {
+    fn from(err: RequestError) -> GetPinholeTimeoutError {
+        GetPinholeTimeoutError::RequestError(err)
+    }
+}
@@ -0,0 +1,50 @@
+//! # igd
+//!
+//! This library allows you to communicate with an IGD enabled device.
+//! Create an `aio::Gateway` with `Gateway::new` from the device's address
+//! and control URL. You can then communicate with the device via this object.
+
+use std::fmt;
+
+pub mod aio;
+mod common;
+pub mod errors;
+pub mod natpmp;
+pub mod pcp;
+pub mod portmapper;
+
+pub use crate::common::parsing::{find_ipv6_firewall_control_url, PortMappingEntry};
+pub use crate::errors::{
+    AddAnyPortError, AddPinholeError, AddPortError, DeletePinholeError, GetExternalIpError,
+    GetGenericPortMappingEntryError, GetPinholeTimeoutError, RemovePortError, RequestError,
+};
+pub use crate::portmapper::{PortMapper, PortMapperError};
+
+/// Represents the protocol used by a port mapping.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PortMappingProtocol {
+    /// TCP protocol
+    TCP,
+    /// UDP protocol
+    UDP,
+}
+
+impl fmt::Display for PortMappingProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PortMappingProtocol::TCP => "TCP",
+            PortMappingProtocol::UDP => "UDP",
+        })
+    }
+}
+
+/// The identifier a `WANIPv6FirewallControl` service assigns to a pinhole
+/// opened with `AddPinhole`, used to later `DeletePinhole` it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct UniqueId(pub u16);
+
+impl fmt::Display for UniqueId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
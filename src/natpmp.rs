@@ -0,0 +1,223 @@
+//! A minimal NAT-PMP (RFC 6886) client, used as a fallback when no UPnP IGD
+//! gateway can be found on the network.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::PortMappingProtocol;
+
+/// The UDP port NAT-PMP (and PCP) servers listen on.
+pub const NATPMP_PORT: u16 = 5351;
+
+const PROTOCOL_VERSION: u8 = 0;
+const OPCODE_EXTERNAL_ADDRESS: u8 = 0;
+const OPCODE_MAP_UDP: u8 = 1;
+const OPCODE_MAP_TCP: u8 = 2;
+const RESPONSE_BIT: u8 = 0x80;
+
+/// Errors returned by [`NatPmpClient`].
+#[derive(Debug)]
+pub enum NatPmpError {
+    /// An IO error occurred while talking to the gateway.
+    IoError(io::Error),
+    /// The gateway's response was too short or otherwise malformed.
+    InvalidResponse,
+    /// The gateway does not support the NAT-PMP protocol version we sent (result code 1).
+    UnsupportedVersion,
+    /// The client is not authorized to perform this action (result code 2).
+    NotAuthorized,
+    /// The gateway suffered a network failure (result code 3).
+    NetworkFailure,
+    /// The gateway is out of resources (result code 4).
+    OutOfResources,
+    /// The opcode we sent is not supported by the gateway (result code 5).
+    UnsupportedOpcode,
+}
+
+impl From<io::Error> for NatPmpError {
+    fn from(err: io::Error) -> NatPmpError {
+        NatPmpError::IoError(err)
+    }
+}
+
+fn result_code_error(code: u16) -> Option<NatPmpError> {
+    match code {
+        0 => None,
+        1 => Some(NatPmpError::UnsupportedVersion),
+        2 => Some(NatPmpError::NotAuthorized),
+        3 => Some(NatPmpError::NetworkFailure),
+        4 => Some(NatPmpError::OutOfResources),
+        5 => Some(NatPmpError::UnsupportedOpcode),
+        _ => Some(NatPmpError::InvalidResponse),
+    }
+}
+
+fn protocol_opcode(protocol: PortMappingProtocol) -> u8 {
+    match protocol {
+        PortMappingProtocol::UDP => OPCODE_MAP_UDP,
+        PortMappingProtocol::TCP => OPCODE_MAP_TCP,
+    }
+}
+
+/// A client for the NAT-PMP protocol, talking to the default gateway on UDP port 5351.
+pub struct NatPmpClient {
+    socket: UdpSocket,
+    gateway: SocketAddr,
+}
+
+impl NatPmpClient {
+    /// Bind a NAT-PMP client that will talk to `gateway` (typically the
+    /// default route's address) on the standard NAT-PMP port.
+    pub async fn new(gateway: Ipv4Addr) -> Result<NatPmpClient, NatPmpError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        Ok(NatPmpClient {
+            socket,
+            gateway: SocketAddr::V4(SocketAddrV4::new(gateway, NATPMP_PORT)),
+        })
+    }
+
+    async fn request(&self, packet: &[u8], min_response_len: usize) -> Result<Vec<u8>, NatPmpError> {
+        self.socket.send_to(packet, self.gateway).await?;
+        let mut buf = [0u8; 16];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(1), self.socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| NatPmpError::InvalidResponse)??;
+        if len < min_response_len {
+            return Err(NatPmpError::InvalidResponse);
+        }
+        Ok(buf[..len].to_vec())
+    }
+
+    /// Request the external IP address of the gateway.
+    pub async fn get_external_ip(&self) -> Result<Ipv4Addr, NatPmpError> {
+        let packet = build_external_address_request();
+        let resp = self.request(&packet, 12).await?;
+        parse_external_address_response(&resp)
+    }
+
+    /// Map `internal_port` to `external_port` (0 lets the gateway choose) for
+    /// `lifetime_secs` seconds. Returns the assigned external port and the
+    /// lifetime actually granted.
+    ///
+    /// A `lifetime_secs` of 0 requests deletion of the mapping.
+    pub async fn add_port(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        external_port: u16,
+        lifetime_secs: u32,
+    ) -> Result<(u16, u32), NatPmpError> {
+        let packet = build_add_port_request(protocol, internal_port, external_port, lifetime_secs);
+        let resp = self.request(&packet, 16).await?;
+        parse_add_port_response(protocol, &resp)
+    }
+
+    /// Remove a previously added mapping, by requesting it again with a
+    /// lifetime of 0.
+    pub async fn remove_port(&self, protocol: PortMappingProtocol, internal_port: u16) -> Result<(), NatPmpError> {
+        self.add_port(protocol, internal_port, 0, 0).await?;
+        Ok(())
+    }
+}
+
+fn build_external_address_request() -> [u8; 2] {
+    [PROTOCOL_VERSION, OPCODE_EXTERNAL_ADDRESS]
+}
+
+fn parse_external_address_response(resp: &[u8]) -> Result<Ipv4Addr, NatPmpError> {
+    if resp[1] != OPCODE_EXTERNAL_ADDRESS | RESPONSE_BIT {
+        return Err(NatPmpError::InvalidResponse);
+    }
+    let result_code = u16::from_be_bytes([resp[2], resp[3]]);
+    if let Some(err) = result_code_error(result_code) {
+        return Err(err);
+    }
+    Ok(Ipv4Addr::new(resp[8], resp[9], resp[10], resp[11]))
+}
+
+fn build_add_port_request(
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+    external_port: u16,
+    lifetime_secs: u32,
+) -> [u8; 12] {
+    let mut packet = [0u8; 12];
+    packet[0] = PROTOCOL_VERSION;
+    packet[1] = protocol_opcode(protocol);
+    packet[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    packet[6..8].copy_from_slice(&external_port.to_be_bytes());
+    packet[8..12].copy_from_slice(&lifetime_secs.to_be_bytes());
+    packet
+}
+
+fn parse_add_port_response(protocol: PortMappingProtocol, resp: &[u8]) -> Result<(u16, u32), NatPmpError> {
+    if resp[1] != protocol_opcode(protocol) | RESPONSE_BIT {
+        return Err(NatPmpError::InvalidResponse);
+    }
+    let result_code = u16::from_be_bytes([resp[2], resp[3]]);
+    if let Some(err) = result_code_error(result_code) {
+        return Err(err);
+    }
+    let granted_external_port = u16::from_be_bytes([resp[10], resp[11]]);
+    let granted_lifetime = u32::from_be_bytes([resp[12], resp[13], resp[14], resp[15]]);
+    Ok((granted_external_port, granted_lifetime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_address_request_is_version_zero_opcode_zero() {
+        assert_eq!(build_external_address_request(), [0, 0]);
+    }
+
+    #[test]
+    fn parses_successful_external_address_response() {
+        let mut resp = [0u8; 12];
+        resp[1] = OPCODE_EXTERNAL_ADDRESS | RESPONSE_BIT;
+        resp[8..12].copy_from_slice(&[203, 0, 113, 42]);
+        assert_eq!(parse_external_address_response(&resp).unwrap(), Ipv4Addr::new(203, 0, 113, 42));
+    }
+
+    #[test]
+    fn external_address_response_reports_unsupported_version() {
+        let mut resp = [0u8; 12];
+        resp[1] = OPCODE_EXTERNAL_ADDRESS | RESPONSE_BIT;
+        resp[3] = 1; // result code 1
+        assert!(matches!(parse_external_address_response(&resp), Err(NatPmpError::UnsupportedVersion)));
+    }
+
+    #[test]
+    fn add_port_request_encodes_protocol_ports_and_lifetime() {
+        let packet = build_add_port_request(PortMappingProtocol::TCP, 1234, 5678, 3600);
+        assert_eq!(packet[1], OPCODE_MAP_TCP);
+        assert_eq!(u16::from_be_bytes([packet[4], packet[5]]), 1234);
+        assert_eq!(u16::from_be_bytes([packet[6], packet[7]]), 5678);
+        assert_eq!(u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]), 3600);
+    }
+
+    #[test]
+    fn parses_successful_add_port_response() {
+        let mut resp = [0u8; 16];
+        resp[1] = OPCODE_MAP_UDP | RESPONSE_BIT;
+        resp[10..12].copy_from_slice(&5678u16.to_be_bytes());
+        resp[12..16].copy_from_slice(&3600u32.to_be_bytes());
+        let (port, lifetime) = parse_add_port_response(PortMappingProtocol::UDP, &resp).unwrap();
+        assert_eq!(port, 5678);
+        assert_eq!(lifetime, 3600);
+    }
+
+    #[test]
+    fn add_port_response_rejects_mismatched_opcode() {
+        let mut resp = [0u8; 16];
+        resp[1] = OPCODE_MAP_TCP | RESPONSE_BIT; // responding to the wrong protocol
+        assert!(matches!(
+            parse_add_port_response(PortMappingProtocol::UDP, &resp),
+            Err(NatPmpError::InvalidResponse)
+        ));
+    }
+}
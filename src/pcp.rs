@@ -0,0 +1,230 @@
+//! A minimal PCP (RFC 6887) client, used as a fallback when a gateway speaks
+//! PCP rather than NAT-PMP (signalled by a NAT-PMP `UnsupportedVersion`
+//! result).
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+
+use crate::natpmp::NATPMP_PORT;
+use crate::PortMappingProtocol;
+
+const PROTOCOL_VERSION: u8 = 2;
+const OPCODE_MAP: u8 = 1;
+const RESPONSE_BIT: u8 = 0x80;
+const REQUEST_HEADER_LEN: usize = 24;
+const MAP_PAYLOAD_LEN: usize = 12 + 1 + 3 + 2 + 2 + 16;
+
+/// Errors returned by [`PcpClient`].
+#[derive(Debug)]
+pub enum PcpError {
+    /// An IO error occurred while talking to the gateway.
+    IoError(io::Error),
+    /// The gateway's response was too short or otherwise malformed.
+    InvalidResponse,
+    /// The gateway does not support our PCP version (result code 1).
+    UnsupportedVersion,
+    /// The client is not authorized to perform this action (result code 2).
+    NotAuthorized,
+    /// The gateway could not parse our request (result code 3).
+    MalformedRequest,
+    /// The requested opcode is not supported (result code 4).
+    UnsupportedOpcode,
+    /// Some other, non-zero result code was returned.
+    ResultCode(u8),
+}
+
+impl From<io::Error> for PcpError {
+    fn from(err: io::Error) -> PcpError {
+        PcpError::IoError(err)
+    }
+}
+
+fn result_code_error(code: u8) -> Option<PcpError> {
+    match code {
+        0 => None,
+        1 => Some(PcpError::UnsupportedVersion),
+        2 => Some(PcpError::NotAuthorized),
+        3 => Some(PcpError::MalformedRequest),
+        4 => Some(PcpError::UnsupportedOpcode),
+        other => Some(PcpError::ResultCode(other)),
+    }
+}
+
+fn protocol_number(protocol: PortMappingProtocol) -> u8 {
+    match protocol {
+        PortMappingProtocol::TCP => 6,
+        PortMappingProtocol::UDP => 17,
+    }
+}
+
+fn ipv4_mapped(ip: Ipv4Addr) -> [u8; 16] {
+    ip.to_ipv6_mapped().octets()
+}
+
+/// A client for the PCP protocol, talking to the default gateway on UDP port 5351.
+pub struct PcpClient {
+    socket: UdpSocket,
+    gateway: SocketAddr,
+    client_ip: Ipv4Addr,
+}
+
+impl PcpClient {
+    /// Bind a PCP client that will talk to `gateway` on the standard PCP
+    /// port, identifying itself with `client_ip` (our address on the
+    /// gateway's LAN).
+    pub async fn new(gateway: Ipv4Addr, client_ip: Ipv4Addr) -> Result<PcpClient, PcpError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        Ok(PcpClient {
+            socket,
+            gateway: SocketAddr::V4(SocketAddrV4::new(gateway, NATPMP_PORT)),
+            client_ip,
+        })
+    }
+
+    /// Map `internal_port` to `suggested_external_port` (0 lets the gateway
+    /// choose) for `lifetime_secs` seconds. Returns the assigned external
+    /// port, assigned external address and the lifetime actually granted.
+    ///
+    /// A `lifetime_secs` of 0 requests deletion of the mapping.
+    pub async fn add_port(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime_secs: u32,
+    ) -> Result<(u16, Ipv4Addr, u32), PcpError> {
+        let nonce: [u8; 12] = rand::random();
+        let packet = build_map_request(self.client_ip, nonce, protocol, internal_port, suggested_external_port, lifetime_secs);
+
+        self.socket.send_to(&packet, self.gateway).await?;
+
+        let mut buf = [0u8; REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(2), self.socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| PcpError::InvalidResponse)??;
+        if len < REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN {
+            return Err(PcpError::InvalidResponse);
+        }
+        parse_map_response(&buf, nonce)
+    }
+
+    /// Remove a previously added mapping, by requesting it again with a
+    /// lifetime of 0.
+    pub async fn remove_port(&self, protocol: PortMappingProtocol, internal_port: u16) -> Result<(), PcpError> {
+        self.add_port(protocol, internal_port, 0, 0).await?;
+        Ok(())
+    }
+}
+
+fn build_map_request(
+    client_ip: Ipv4Addr,
+    nonce: [u8; 12],
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+    suggested_external_port: u16,
+    lifetime_secs: u32,
+) -> [u8; REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN] {
+    let mut packet = [0u8; REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN];
+
+    packet[0] = PROTOCOL_VERSION;
+    packet[1] = OPCODE_MAP;
+    packet[4..8].copy_from_slice(&lifetime_secs.to_be_bytes());
+    packet[8..24].copy_from_slice(&ipv4_mapped(client_ip));
+
+    let payload = &mut packet[REQUEST_HEADER_LEN..];
+    payload[0..12].copy_from_slice(&nonce);
+    payload[12] = protocol_number(protocol);
+    payload[16..18].copy_from_slice(&internal_port.to_be_bytes());
+    payload[18..20].copy_from_slice(&suggested_external_port.to_be_bytes());
+    payload[20..36].copy_from_slice(&ipv4_mapped(Ipv4Addr::UNSPECIFIED));
+
+    packet
+}
+
+fn parse_map_response(buf: &[u8], expected_nonce: [u8; 12]) -> Result<(u16, Ipv4Addr, u32), PcpError> {
+    if buf[1] != OPCODE_MAP | RESPONSE_BIT {
+        return Err(PcpError::InvalidResponse);
+    }
+    if let Some(err) = result_code_error(buf[3]) {
+        return Err(err);
+    }
+    let granted_lifetime = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+    let payload = &buf[REQUEST_HEADER_LEN..];
+    if payload[0..12] != expected_nonce {
+        return Err(PcpError::InvalidResponse);
+    }
+    let granted_external_port = u16::from_be_bytes([payload[18], payload[19]]);
+    let external_ip_bytes: [u8; 16] = payload[20..36].try_into().map_err(|_| PcpError::InvalidResponse)?;
+    let external_ip = match std::net::Ipv6Addr::from(external_ip_bytes).to_ipv4_mapped() {
+        Some(ip) => ip,
+        None => return Err(PcpError::InvalidResponse),
+    };
+
+    Ok((granted_external_port, external_ip, granted_lifetime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_request_encodes_header_and_payload_fields() {
+        let nonce = [7u8; 12];
+        let packet = build_map_request(
+            Ipv4Addr::new(192, 168, 1, 2),
+            nonce,
+            PortMappingProtocol::TCP,
+            1234,
+            5678,
+            3600,
+        );
+        assert_eq!(packet[0], PROTOCOL_VERSION);
+        assert_eq!(packet[1], OPCODE_MAP);
+        assert_eq!(u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]), 3600);
+
+        let payload = &packet[REQUEST_HEADER_LEN..];
+        assert_eq!(&payload[0..12], &nonce);
+        assert_eq!(payload[12], 6); // IPPROTO_TCP
+        assert_eq!(u16::from_be_bytes([payload[16], payload[17]]), 1234);
+        assert_eq!(u16::from_be_bytes([payload[18], payload[19]]), 5678);
+    }
+
+    fn map_response(nonce: [u8; 12], result_code: u8, lifetime: u32, external_port: u16, external_ip: Ipv4Addr) -> [u8; REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN] {
+        let mut buf = [0u8; REQUEST_HEADER_LEN + MAP_PAYLOAD_LEN];
+        buf[1] = OPCODE_MAP | RESPONSE_BIT;
+        buf[3] = result_code;
+        buf[4..8].copy_from_slice(&lifetime.to_be_bytes());
+        let payload = &mut buf[REQUEST_HEADER_LEN..];
+        payload[0..12].copy_from_slice(&nonce);
+        payload[18..20].copy_from_slice(&external_port.to_be_bytes());
+        payload[20..36].copy_from_slice(&ipv4_mapped(external_ip));
+        buf
+    }
+
+    #[test]
+    fn parses_successful_map_response() {
+        let nonce = [9u8; 12];
+        let buf = map_response(nonce, 0, 3600, 5678, Ipv4Addr::new(203, 0, 113, 42));
+        let (port, ip, lifetime) = parse_map_response(&buf, nonce).unwrap();
+        assert_eq!(port, 5678);
+        assert_eq!(ip, Ipv4Addr::new(203, 0, 113, 42));
+        assert_eq!(lifetime, 3600);
+    }
+
+    #[test]
+    fn map_response_rejects_mismatched_nonce() {
+        let buf = map_response([1u8; 12], 0, 3600, 5678, Ipv4Addr::new(203, 0, 113, 42));
+        assert!(matches!(parse_map_response(&buf, [2u8; 12]), Err(PcpError::InvalidResponse)));
+    }
+
+    #[test]
+    fn map_response_reports_unsupported_version() {
+        let nonce = [3u8; 12];
+        let buf = map_response(nonce, 1, 0, 0, Ipv4Addr::UNSPECIFIED);
+        assert!(matches!(parse_map_response(&buf, nonce), Err(PcpError::UnsupportedVersion)));
+    }
+}
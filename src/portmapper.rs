@@ -0,0 +1,111 @@
+//! A `Gateway`-shaped client that speaks NAT-PMP, falling back to PCP when
+//! the gateway reports it only understands the newer protocol.
+//!
+//! Use this when [`crate::aio::Gateway`] discovery fails to find a UPnP IGD
+//! on the network but the router still supports NAT-PMP or PCP.
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use crate::natpmp::{NatPmpClient, NatPmpError};
+use crate::pcp::{PcpClient, PcpError};
+use crate::PortMappingProtocol;
+
+/// Errors returned by [`PortMapper`].
+#[derive(Debug)]
+pub enum PortMapperError {
+    /// An error occurred while performing a PCP request (after NAT-PMP reported `UnsupportedVersion`).
+    Pcp(PcpError),
+    /// An error occurred while performing a NAT-PMP request.
+    NatPmp(NatPmpError),
+}
+
+impl fmt::Display for PortMapperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortMapperError::Pcp(err) => write!(f, "PCP error: {:?}", err),
+            PortMapperError::NatPmp(err) => write!(f, "NAT-PMP error: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for PortMapperError {}
+
+/// Lifetime, in seconds, used for the throwaway PCP mapping created to probe
+/// the external IP address. A lifetime of 0 is a *deletion* request per
+/// RFC 6887 and is not guaranteed to return a usable address, so a short
+/// non-zero lifetime is used instead and the mapping is deleted immediately
+/// after.
+const PROBE_LIFETIME_SECS: u32 = 5;
+
+/// A NAT-PMP client that transparently falls back to PCP.
+pub struct PortMapper {
+    nat_pmp: NatPmpClient,
+    pcp: PcpClient,
+}
+
+impl PortMapper {
+    /// Connect to `gateway` (the default route's address), identifying
+    /// ourselves to PCP as `client_ip` (our address on the gateway's LAN).
+    pub async fn new(gateway: Ipv4Addr, client_ip: Ipv4Addr) -> Result<PortMapper, PortMapperError> {
+        let nat_pmp = NatPmpClient::new(gateway).await.map_err(PortMapperError::NatPmp)?;
+        let pcp = PcpClient::new(gateway, client_ip).await.map_err(PortMapperError::Pcp)?;
+        Ok(PortMapper { nat_pmp, pcp })
+    }
+
+    /// Get the external IP address of the gateway, trying NAT-PMP first and
+    /// falling back to PCP if NAT-PMP reports an unsupported version. PCP
+    /// has no standalone "get address" opcode, so the PCP path maps UDP
+    /// `probe_port` for `PROBE_LIFETIME_SECS` seconds, reads the external IP from
+    /// the response, and then deletes that mapping.
+    pub async fn get_external_ip(&self, probe_port: u16) -> Result<Ipv4Addr, PortMapperError> {
+        match self.nat_pmp.get_external_ip().await {
+            Ok(ip) => Ok(ip),
+            Err(NatPmpError::UnsupportedVersion) => {
+                let (_, ip, _) = self
+                    .pcp
+                    .add_port(PortMappingProtocol::UDP, probe_port, probe_port, PROBE_LIFETIME_SECS)
+                    .await
+                    .map_err(PortMapperError::Pcp)?;
+                // Best-effort: tear the throwaway probe mapping back down.
+                let _ = self.pcp.add_port(PortMappingProtocol::UDP, probe_port, probe_port, 0).await;
+                Ok(ip)
+            }
+            Err(err) => Err(PortMapperError::NatPmp(err)),
+        }
+    }
+
+    /// Map `internal_port` to `external_port` (0 lets the gateway choose)
+    /// for `lifetime_secs` seconds, trying NAT-PMP first and falling back to
+    /// PCP only if the gateway reports an unsupported version (NAT-PMP result
+    /// code 1). Any other NAT-PMP error is returned as-is, matching
+    /// [`Self::get_external_ip`]'s fallback policy. Returns the assigned
+    /// external port.
+    pub async fn add_port(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        external_port: u16,
+        lifetime_secs: u32,
+    ) -> Result<u16, PortMapperError> {
+        match self.nat_pmp.add_port(protocol, internal_port, external_port, lifetime_secs).await {
+            Ok((port, _)) => Ok(port),
+            Err(NatPmpError::UnsupportedVersion) => {
+                let (port, _, _) = self
+                    .pcp
+                    .add_port(protocol, internal_port, external_port, lifetime_secs)
+                    .await
+                    .map_err(PortMapperError::Pcp)?;
+                Ok(port)
+            }
+            Err(err) => Err(PortMapperError::NatPmp(err)),
+        }
+    }
+
+    /// Remove a previously added mapping. A mapping is deleted by
+    /// re-requesting it with a lifetime of 0, which both NAT-PMP and PCP
+    /// treat as a removal.
+    pub async fn remove_port(&self, protocol: PortMappingProtocol, internal_port: u16) -> Result<(), PortMapperError> {
+        self.add_port(protocol, internal_port, 0, 0).await.map(|_| ())
+    }
+}